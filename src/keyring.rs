@@ -1,23 +1,63 @@
-use std::{collections::HashMap, fmt};
+use std::{collections::HashMap, fmt, fs, path::PathBuf, str::FromStr};
 use tokio::runtime::Runtime;
 
+use crate::otp::Algorithm;
+use crate::otpauth::OtpType;
+
 // TODO: Use async?
 
+/// Which credential backend a `Keyring` is backed by.
+enum Backend {
+    SecretService(oo7::Keyring),
+    File(oo7::file::Keyring),
+}
+
+/// Which backend to try when opening a `Keyring`. `Auto` is the default:
+/// it tries the D-Bus Secret Service first and falls back to the
+/// passphrase-protected file backend when that is unavailable (e.g. in a
+/// headless or sandboxed environment with no running secret service daemon).
+pub enum BackendKind {
+    Auto,
+    SecretService,
+    File,
+}
+
 pub struct Keyring {
-    keyring: oo7::Keyring,
+    backend: Backend,
     runtime: Runtime,
 }
 
 pub struct Secret {
     name: String,
     secret: String,
+    algorithm: Algorithm,
+    digits: u8,
+    period: u64,
+    is_steam: bool,
+    otp_type: OtpType,
+    counter: u64,
 }
 
 impl Secret {
-    fn new(name: &str, secret: &[u8]) -> Self {
+    fn new(
+        name: &str,
+        secret: &[u8],
+        algorithm: Algorithm,
+        digits: u8,
+        period: u64,
+        is_steam: bool,
+        otp_type: OtpType,
+        counter: u64,
+    ) -> Self {
         Self {
             name: String::from(name),
             secret: String::from_utf8(secret.to_vec()).unwrap(),
+            algorithm,
+            digits,
+            period,
+            is_steam,
+            otp_type,
+            counter,
         }
     }
 
@@ -28,6 +68,80 @@ impl Secret {
     pub fn secret(&self) -> &str {
         &self.secret
     }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn digits(&self) -> u8 {
+        self.digits
+    }
+
+    pub fn period(&self) -> u64 {
+        self.period
+    }
+
+    pub fn is_steam(&self) -> bool {
+        self.is_steam
+    }
+
+    pub fn otp_type(&self) -> OtpType {
+        self.otp_type
+    }
+
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+}
+
+fn algorithm_from_attributes(attr: &HashMap<String, String>) -> Algorithm {
+    attr.get("algorithm")
+        .and_then(|a| Algorithm::from_str(a).ok())
+        .unwrap_or_default()
+}
+
+fn digits_from_attributes(attr: &HashMap<String, String>) -> u8 {
+    attr.get("digits")
+        .and_then(|d| d.parse().ok())
+        .unwrap_or(crate::DEFAULT_DIGITS)
+}
+
+fn period_from_attributes(attr: &HashMap<String, String>) -> u64 {
+    attr.get("period")
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(crate::DEFAULT_INTERVAL)
+}
+
+fn is_steam_from_attributes(attr: &HashMap<String, String>) -> bool {
+    attr.get("code_format")
+        .map(|f| f == "steam")
+        .unwrap_or(false)
+}
+
+fn otp_type_from_attributes(attr: &HashMap<String, String>) -> OtpType {
+    match attr.get("otp_type").map(String::as_str) {
+        Some("hotp") => OtpType::Hotp,
+        _ => OtpType::Totp,
+    }
+}
+
+fn counter_from_attributes(attr: &HashMap<String, String>) -> u64 {
+    attr.get("counter")
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(0)
+}
+
+fn secret_from_parts(name: &str, secret: &[u8], attr: &HashMap<String, String>) -> Secret {
+    Secret::new(
+        name,
+        secret,
+        algorithm_from_attributes(attr),
+        digits_from_attributes(attr),
+        period_from_attributes(attr),
+        is_steam_from_attributes(attr),
+        otp_type_from_attributes(attr),
+        counter_from_attributes(attr),
+    )
 }
 
 #[derive(Debug, Clone)]
@@ -57,7 +171,7 @@ impl Keyring {
     const COMMON_ATTRIBUTE_KEY: &'static str = "application_id";
     const COMMON_ATTRIBUTE_VALUE: &'static str = "25fa6cf5-ba20-481d-b382-f3acab4da54e";
 
-    pub fn new() -> Result<Self, KeyringError> {
+    pub fn new(kind: BackendKind) -> Result<Self, KeyringError> {
         let runtime = match tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build()
@@ -68,14 +182,49 @@ impl Keyring {
             }
         };
 
-        let keyring = match runtime.block_on(oo7::Keyring::new()) {
-            Ok(k) => k,
-            Err(e) => {
-                return Err(KeyringError::from_string(e.to_string()));
-            }
+        let backend = match kind {
+            BackendKind::SecretService => Self::connect_secret_service(&runtime)?,
+            BackendKind::File => Self::open_file_backend(&runtime)?,
+            BackendKind::Auto => match Self::connect_secret_service(&runtime) {
+                Ok(backend) => backend,
+                Err(_) => Self::open_file_backend(&runtime)?,
+            },
         };
 
-        Ok(Keyring { keyring, runtime })
+        Ok(Keyring { backend, runtime })
+    }
+
+    fn connect_secret_service(runtime: &Runtime) -> Result<Backend, KeyringError> {
+        match runtime.block_on(oo7::Keyring::new()) {
+            Ok(k) => Ok(Backend::SecretService(k)),
+            Err(e) => Err(KeyringError::from_string(e.to_string())),
+        }
+    }
+
+    fn file_backend_path() -> Result<PathBuf, KeyringError> {
+        let Some(data_dir) = dirs::data_dir() else {
+            return Err(KeyringError::from_slice(
+                "Could not determine the user's data directory",
+            ));
+        };
+
+        let dir = data_dir.join("rauthp");
+        if let Err(e) = fs::create_dir_all(&dir) {
+            return Err(KeyringError::from_string(e.to_string()));
+        }
+
+        Ok(dir.join("keyring"))
+    }
+
+    fn open_file_backend(runtime: &Runtime) -> Result<Backend, KeyringError> {
+        let path = Self::file_backend_path()?;
+        let passphrase = rpassword::prompt_password("File keyring passphrase: ")
+            .map_err(|e| KeyringError::from_string(e.to_string()))?;
+
+        match runtime.block_on(oo7::file::Keyring::load(&path, passphrase)) {
+            Ok(k) => Ok(Backend::File(k)),
+            Err(e) => Err(KeyringError::from_string(e.to_string())),
+        }
     }
 
     fn secret_attributes(name: &str) -> HashMap<&str, &str> {
@@ -88,7 +237,12 @@ impl Keyring {
         HashMap::from([(Self::COMMON_ATTRIBUTE_KEY, Self::COMMON_ATTRIBUTE_VALUE)])
     }
 
-    pub fn store_secret(&self, name: &str, secret: &str) -> Result<(), KeyringError> {
+    pub fn store_secret(
+        &self,
+        name: &str,
+        secret: &str,
+        extra_attributes: &HashMap<&str, &str>,
+    ) -> Result<(), KeyringError> {
         match self.get_secret(name) {
             Ok(s) => {
                 if s.is_some() {
@@ -100,80 +254,193 @@ impl Keyring {
             }
         }
 
-        let attributes = Self::secret_attributes(name);
-        match self
-            .runtime
-            .block_on(self.keyring.create_item(name, &attributes, secret, false))
-        {
-            Ok(()) => Ok(()),
-            Err(e) => Err(KeyringError::from_string(e.to_string())),
+        let mut attributes = Self::secret_attributes(name);
+        attributes.extend(extra_attributes.iter().map(|(k, v)| (*k, *v)));
+
+        match &self.backend {
+            Backend::SecretService(k) => self
+                .runtime
+                .block_on(k.create_item(name, &attributes, secret, false))
+                .map_err(|e| KeyringError::from_string(e.to_string())),
+            Backend::File(k) => self
+                .runtime
+                .block_on(k.create_item(name, &attributes, secret, false))
+                .map_err(|e| KeyringError::from_string(e.to_string())),
+        }
+    }
+
+    pub fn update_counter(&self, name: &str, counter: u64) -> Result<(), KeyringError> {
+        let search_attributes = Self::secret_attributes(name);
+
+        match &self.backend {
+            Backend::SecretService(k) => {
+                let items = self
+                    .runtime
+                    .block_on(k.search_items(&search_attributes))
+                    .map_err(|e| KeyringError::from_string(e.to_string()))?;
+                let Some(item) = items.first() else {
+                    return Err(KeyringError::from_slice("No such secret"));
+                };
+                let mut attributes = self
+                    .runtime
+                    .block_on(item.attributes())
+                    .map_err(|e| KeyringError::from_string(e.to_string()))?;
+                attributes.insert(String::from("counter"), counter.to_string());
+                let attributes: HashMap<&str, &str> = attributes
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+                    .collect();
+                self.runtime
+                    .block_on(item.set_attributes(&attributes))
+                    .map_err(|e| KeyringError::from_string(e.to_string()))
+            }
+            Backend::File(k) => {
+                let items = self
+                    .runtime
+                    .block_on(k.search_items(&search_attributes))
+                    .map_err(|e| KeyringError::from_string(e.to_string()))?;
+                let Some(item) = items.first() else {
+                    return Err(KeyringError::from_slice("No such secret"));
+                };
+                let mut attributes = self
+                    .runtime
+                    .block_on(item.attributes())
+                    .map_err(|e| KeyringError::from_string(e.to_string()))?;
+                attributes.insert(String::from("counter"), counter.to_string());
+                let attributes: HashMap<&str, &str> = attributes
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+                    .collect();
+                self.runtime
+                    .block_on(item.set_attributes(&attributes))
+                    .map_err(|e| KeyringError::from_string(e.to_string()))
+            }
         }
     }
 
     pub fn delete_secret(&self, name: &str) -> Result<(), KeyringError> {
         let attributes = Self::secret_attributes(name);
-        match self.runtime.block_on(self.keyring.delete(&attributes)) {
-            Ok(()) => Ok(()),
-            Err(e) => Err(KeyringError::from_string(e.to_string())),
+        match &self.backend {
+            Backend::SecretService(k) => self
+                .runtime
+                .block_on(k.delete(&attributes))
+                .map_err(|e| KeyringError::from_string(e.to_string())),
+            Backend::File(k) => self
+                .runtime
+                .block_on(k.delete(&attributes))
+                .map_err(|e| KeyringError::from_string(e.to_string())),
         }
     }
 
     pub fn get_all_secrets(&self) -> Result<Vec<Secret>, KeyringError> {
         let attributes = Self::secrets_common_attribute();
-        let request_result = self
-            .runtime
-            .block_on(self.keyring.search_items(&attributes));
-
-        match request_result {
-            Ok(secrets) => secrets
-                .iter()
-                .map(|elt| {
-                    let Ok(secret) = self.runtime.block_on(elt.secret()) else {
-                        return Err(KeyringError::from_slice(
-                            "Failed to retrieve the value of a secret",
-                        ));
-                    };
-                    let Ok(attr) = self.runtime.block_on(elt.attributes()) else {
-                        return Err(KeyringError::from_slice(
-                            "Failed to retrive the name of a secret",
-                        ));
-                    };
-                    let Some(name) = attr.get(Self::ATTRIBUTE_KEY) else {
-                        return Err(KeyringError::from_slice(
-                            "Unexpected data retrieved from the keyring",
-                        ));
-                    };
-                    Ok(Secret::new(name, &secret))
-                })
-                .collect::<Result<Vec<Secret>, _>>(),
-            Err(e) => Err(KeyringError::from_string(e.to_string())),
+
+        match &self.backend {
+            Backend::SecretService(k) => {
+                let secrets = self
+                    .runtime
+                    .block_on(k.search_items(&attributes))
+                    .map_err(|e| KeyringError::from_string(e.to_string()))?;
+                secrets
+                    .iter()
+                    .map(|elt| {
+                        let Ok(secret) = self.runtime.block_on(elt.secret()) else {
+                            return Err(KeyringError::from_slice(
+                                "Failed to retrieve the value of a secret",
+                            ));
+                        };
+                        let Ok(attr) = self.runtime.block_on(elt.attributes()) else {
+                            return Err(KeyringError::from_slice(
+                                "Failed to retrive the name of a secret",
+                            ));
+                        };
+                        let Some(name) = attr.get(Self::ATTRIBUTE_KEY) else {
+                            return Err(KeyringError::from_slice(
+                                "Unexpected data retrieved from the keyring",
+                            ));
+                        };
+                        Ok(secret_from_parts(name, &secret, &attr))
+                    })
+                    .collect::<Result<Vec<Secret>, _>>()
+            }
+            Backend::File(k) => {
+                let secrets = self
+                    .runtime
+                    .block_on(k.search_items(&attributes))
+                    .map_err(|e| KeyringError::from_string(e.to_string()))?;
+                secrets
+                    .iter()
+                    .map(|elt| {
+                        let Ok(secret) = self.runtime.block_on(elt.secret()) else {
+                            return Err(KeyringError::from_slice(
+                                "Failed to retrieve the value of a secret",
+                            ));
+                        };
+                        let Ok(attr) = self.runtime.block_on(elt.attributes()) else {
+                            return Err(KeyringError::from_slice(
+                                "Failed to retrive the name of a secret",
+                            ));
+                        };
+                        let Some(name) = attr.get(Self::ATTRIBUTE_KEY) else {
+                            return Err(KeyringError::from_slice(
+                                "Unexpected data retrieved from the keyring",
+                            ));
+                        };
+                        Ok(secret_from_parts(name, &secret, &attr))
+                    })
+                    .collect::<Result<Vec<Secret>, _>>()
+            }
         }
     }
 
     pub fn get_secret(&self, name: &str) -> Result<Option<Secret>, KeyringError> {
         let attributes = Self::secret_attributes(name);
-        match self
-            .runtime
-            .block_on(self.keyring.search_items(&attributes))
-        {
-            Ok(request_result) => {
+
+        match &self.backend {
+            Backend::SecretService(k) => {
+                let request_result = self
+                    .runtime
+                    .block_on(k.search_items(&attributes))
+                    .map_err(|e| KeyringError::from_string(e.to_string()))?;
                 if request_result.is_empty() {
                     return Ok(None);
                 }
-
                 if request_result.len() > 1 {
                     return Err(KeyringError::from_slice("Too many results"));
                 }
-
-                let Ok(secret) = self
+                let item = request_result.first().unwrap();
+                let Ok(secret) = self.runtime.block_on(item.secret()) else {
+                    return Err(KeyringError::from_slice("Failed the value of the secret"));
+                };
+                let Ok(attr) = self.runtime.block_on(item.attributes()) else {
+                    return Err(KeyringError::from_slice(
+                        "Failed to retrive the attributes of a secret",
+                    ));
+                };
+                Ok(Some(secret_from_parts(name, &secret, &attr)))
+            }
+            Backend::File(k) => {
+                let request_result = self
                     .runtime
-                    .block_on(request_result.get(0).unwrap().secret())
-                else {
+                    .block_on(k.search_items(&attributes))
+                    .map_err(|e| KeyringError::from_string(e.to_string()))?;
+                if request_result.is_empty() {
+                    return Ok(None);
+                }
+                if request_result.len() > 1 {
+                    return Err(KeyringError::from_slice("Too many results"));
+                }
+                let item = request_result.first().unwrap();
+                let Ok(secret) = self.runtime.block_on(item.secret()) else {
                     return Err(KeyringError::from_slice("Failed the value of the secret"));
                 };
-                Ok(Some(Secret::new(name, &secret)))
+                let Ok(attr) = self.runtime.block_on(item.attributes()) else {
+                    return Err(KeyringError::from_slice(
+                        "Failed to retrive the attributes of a secret",
+                    ));
+                };
+                Ok(Some(secret_from_parts(name, &secret, &attr)))
             }
-            Err(e) => Err(KeyringError::from_string(e.to_string())),
         }
     }
 }
@@ -1,17 +1,27 @@
+use std::collections::HashMap;
 use std::process::exit;
+use std::str::FromStr;
 
 use base32ct::{Base32Upper, Encoding};
 use clap::{arg, ArgMatches, Command};
-use keyring::Keyring;
+use keyring::{BackendKind, Keyring};
 use lazy_static::lazy_static;
-use otp::OtpGenerator;
+use otp::{Algorithm, OtpGenerator};
+use otpauth::{OtpAuthUri, OtpType};
 use regex::Regex;
 
 mod keyring;
 mod otp;
+mod otpauth;
 
 const DEFAULT_INTERVAL: u64 = 30;
 const DEFAULT_DIGITS: u8 = 6;
+const DEFAULT_VERIFY_WINDOW: u64 = 1;
+// RFC 4226 section 5.3 recommends a minimum of 6 digits, but any length from
+// 1 is accepted here; 8 is the largest value in common use (and keeps
+// `10_u64.pow(digits)` nowhere near overflow).
+const MIN_DIGITS: u8 = 1;
+const MAX_DIGITS: u8 = 8;
 
 lazy_static! {
     static ref BASE32_REGEX: Regex = Regex::new(r"^[A-Z2-7]+=*$").unwrap();
@@ -20,19 +30,45 @@ lazy_static! {
 const SUBCOMMAND_GEN: &'static str = "gen";
 const SUBCOMMAND_ADD: &'static str = "add";
 const SUBCOMMAND_DEL: &'static str = "del";
+const SUBCOMMAND_VERIFY: &'static str = "verify";
 
 fn get_cli_args() -> Command {
     Command::new("rauthp")
         .about("CLI TOTP generator")
         .subcommand_required(true)
         .arg_required_else_help(true)
+        .arg(
+            arg!(--backend <BACKEND> "Credential backend: secret-service, file, or auto (default)")
+                .required(false),
+        )
         .subcommand(Command::new(SUBCOMMAND_GEN).about("Generate TOTP codes"))
         .subcommand(
             Command::new(SUBCOMMAND_ADD)
                 .about("Register an account")
                 .arg_required_else_help(true)
-                .arg(arg!(name: <NAME> "Account name"))
-                .arg(arg!(secret: <SECRET> "Base32 encoded secret")),
+                .arg(arg!([name] "Account name"))
+                .arg(arg!([secret] "Base32 encoded secret"))
+                .arg(
+                    arg!(-u --uri <URI> "Import an account from an otpauth:// URI")
+                        .required(false),
+                )
+                .arg(
+                    arg!(-a --algorithm <ALGORITHM> "Hash algorithm: SHA1, SHA256 or SHA512")
+                        .required(false),
+                )
+                .arg(
+                    arg!(-d --digits <DIGITS> "Number of digits in the generated code")
+                        .required(false),
+                )
+                .arg(
+                    arg!(-p --period <PERIOD> "Validity period of the generated code, in seconds")
+                        .required(false),
+                )
+                .arg(arg!(-s --steam "Account uses Steam Guard codes instead of decimal digits"))
+                .arg(
+                    arg!(-c --counter <COUNTER> "Register as a counter-based (HOTP) account starting at this value")
+                        .required(false),
+                ),
         )
         .subcommand(
             Command::new(SUBCOMMAND_DEL)
@@ -40,6 +76,17 @@ fn get_cli_args() -> Command {
                 .arg_required_else_help(true)
                 .arg(arg!(name: <NAME> "Account name")),
         )
+        .subcommand(
+            Command::new(SUBCOMMAND_VERIFY)
+                .about("Verify a code against an account's secret")
+                .arg_required_else_help(true)
+                .arg(arg!(name: <NAME> "Account name"))
+                .arg(arg!(code: <CODE> "Code to verify"))
+                .arg(
+                    arg!(-w --window <WINDOW> "Number of adjacent time steps to tolerate (default: 1)")
+                        .required(false),
+                ),
+        )
 }
 
 fn handle_gen_cmd(keyring: &Keyring) -> bool {
@@ -61,15 +108,54 @@ fn handle_gen_cmd(keyring: &Keyring) -> bool {
                     return false;
                 }
             };
-            let otpgen = OtpGenerator::new(&decoded_secret, DEFAULT_INTERVAL, DEFAULT_DIGITS);
-            match otpgen.generate() {
-                Ok(code) => {
-                    println!("{:<35}: {}", elt.name(), code);
-                    true
+            let otpgen = OtpGenerator::new(
+                &decoded_secret,
+                elt.period(),
+                elt.digits(),
+                elt.algorithm(),
+            );
+            if elt.is_steam() {
+                match otpgen.generate_steam() {
+                    Ok(code) => {
+                        println!("{:<35}: {}", elt.name(), code);
+                        true
+                    }
+                    Err(()) => {
+                        eprintln!("{:<35}: Code generation error", elt.name());
+                        false
+                    }
+                }
+            } else if elt.otp_type() == OtpType::Hotp {
+                match otpgen.generate_hotp(elt.counter()) {
+                    Ok(code) => {
+                        println!("{:<35}: {}", elt.name(), code);
+                        match keyring.update_counter(elt.name(), elt.counter() + 1) {
+                            Ok(()) => true,
+                            Err(e) => {
+                                eprintln!(
+                                    "Failed to persist the updated counter for {}: {}",
+                                    elt.name(),
+                                    e
+                                );
+                                false
+                            }
+                        }
+                    }
+                    Err(()) => {
+                        eprintln!("{:<35}: Code generation error", elt.name());
+                        false
+                    }
                 }
-                Err(()) => {
-                    eprintln!("{:<35}: Code generation error", elt.name());
-                    false
+            } else {
+                match otpgen.generate() {
+                    Ok(code) => {
+                        println!("{:<35}: {}", elt.name(), code);
+                        true
+                    }
+                    Err(()) => {
+                        eprintln!("{:<35}: Code generation error", elt.name());
+                        false
+                    }
                 }
             }
         })
@@ -77,6 +163,10 @@ fn handle_gen_cmd(keyring: &Keyring) -> bool {
     count_success == all_secrets.len()
 }
 
+fn check_digits(digits: u8) -> bool {
+    (MIN_DIGITS..=MAX_DIGITS).contains(&digits)
+}
+
 fn check_base_32_string(input: &str) -> bool {
     if !BASE32_REGEX.is_match(input) {
         return false;
@@ -99,12 +189,17 @@ fn check_base_32_string(input: &str) -> bool {
 }
 
 fn handle_add_cmd(keyring: &Keyring, cmd_args: &ArgMatches) -> bool {
-    let name = cmd_args
-        .get_one::<String>("name")
-        .expect("Failed to parse the secret name");
-    let secret = cmd_args
-        .get_one::<String>("secret")
-        .expect("Failed to parse the secret value");
+    if let Some(uri) = cmd_args.get_one::<String>("uri") {
+        return handle_add_from_uri(keyring, uri);
+    }
+
+    let (Some(name), Some(secret)) = (
+        cmd_args.get_one::<String>("name"),
+        cmd_args.get_one::<String>("secret"),
+    ) else {
+        eprintln!("Either provide NAME and SECRET, or pass --uri");
+        return false;
+    };
 
     let secret = &secret.to_uppercase();
     if !check_base_32_string(&secret) {
@@ -112,7 +207,126 @@ fn handle_add_cmd(keyring: &Keyring, cmd_args: &ArgMatches) -> bool {
         return false;
     }
 
-    match keyring.store_secret(name, secret) {
+    let algorithm = cmd_args.get_one::<String>("algorithm");
+    if let Some(algorithm) = algorithm {
+        if Algorithm::from_str(algorithm).is_err() {
+            eprintln!("Invalid algorithm, should be one of SHA1, SHA256 or SHA512");
+            return false;
+        }
+    }
+
+    let digits = cmd_args.get_one::<String>("digits");
+    if let Some(digits) = digits {
+        match digits.parse::<u8>() {
+            Ok(d) if check_digits(d) => {}
+            _ => {
+                eprintln!(
+                    "Invalid digits, should be an integer between {} and {}",
+                    MIN_DIGITS, MAX_DIGITS
+                );
+                return false;
+            }
+        }
+    }
+
+    let period = cmd_args.get_one::<String>("period");
+    if let Some(period) = period {
+        match period.parse::<u64>() {
+            Ok(p) if p > 0 => {}
+            _ => {
+                eprintln!("Invalid period, should be a positive integer");
+                return false;
+            }
+        }
+    }
+
+    let is_steam = cmd_args.get_flag("steam");
+
+    let counter = cmd_args.get_one::<String>("counter");
+    if let Some(counter) = counter {
+        if counter.parse::<u64>().is_err() {
+            eprintln!("Invalid counter, should be a positive integer");
+            return false;
+        }
+    }
+
+    let mut attributes: HashMap<&str, &str> = HashMap::new();
+    if let Some(algorithm) = algorithm {
+        attributes.insert("algorithm", algorithm);
+    }
+    if let Some(digits) = digits {
+        attributes.insert("digits", digits);
+    }
+    if let Some(period) = period {
+        attributes.insert("period", period);
+    }
+    if is_steam {
+        attributes.insert("code_format", "steam");
+    }
+    if let Some(counter) = counter {
+        attributes.insert("otp_type", "hotp");
+        attributes.insert("counter", counter);
+    }
+
+    match keyring.store_secret(name, secret, &attributes) {
+        Ok(()) => println!("Secret added"),
+        Err(e) => eprintln!("Failed to add the secret to the keyring: {}", e),
+    };
+
+    true
+}
+
+fn handle_add_from_uri(keyring: &Keyring, uri: &str) -> bool {
+    let parsed = match OtpAuthUri::parse(uri) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Failed to parse the otpauth URI: {}", e);
+            return false;
+        }
+    };
+
+    let secret = &parsed.secret.to_uppercase();
+    if !check_base_32_string(secret) {
+        eprintln!("Invalid secret format, should be a valid base32 string");
+        return false;
+    }
+
+    if let Some(digits) = parsed.digits {
+        if !check_digits(digits) {
+            eprintln!(
+                "Invalid digits in the otpauth URI, should be an integer between {} and {}",
+                MIN_DIGITS, MAX_DIGITS
+            );
+            return false;
+        }
+    }
+
+    let digits_str = parsed.digits.map(|d| d.to_string());
+    let period_str = parsed.period.map(|p| p.to_string());
+    let counter_str = parsed.counter.map(|c| c.to_string());
+    let otp_type = match parsed.otp_type {
+        OtpType::Totp => "totp",
+        OtpType::Hotp => "hotp",
+    };
+
+    let mut attributes: HashMap<&str, &str> = HashMap::from([("otp_type", otp_type)]);
+    if let Some(issuer) = &parsed.issuer {
+        attributes.insert("issuer", issuer);
+    }
+    if let Some(algorithm) = &parsed.algorithm {
+        attributes.insert("algorithm", algorithm);
+    }
+    if let Some(digits) = &digits_str {
+        attributes.insert("digits", digits);
+    }
+    if let Some(period) = &period_str {
+        attributes.insert("period", period);
+    }
+    if let Some(counter) = &counter_str {
+        attributes.insert("counter", counter);
+    }
+
+    match keyring.store_secret(&parsed.account, secret, &attributes) {
         Ok(()) => println!("Secret added"),
         Err(e) => eprintln!("Failed to add the secret to the keyring: {}", e),
     };
@@ -136,8 +350,94 @@ fn handle_del_cmd(keyring: &Keyring, cmd_args: &ArgMatches) -> bool {
     }
 }
 
+fn handle_verify_cmd(keyring: &Keyring, cmd_args: &ArgMatches) -> bool {
+    let name = cmd_args
+        .get_one::<String>("name")
+        .expect("Failed to parse the account name");
+    let code = cmd_args
+        .get_one::<String>("code")
+        .expect("Failed to parse the code");
+
+    let window = match cmd_args.get_one::<String>("window") {
+        Some(window) => match window.parse::<u64>() {
+            Ok(window) => window,
+            Err(_) => {
+                eprintln!("Invalid window, should be a positive integer");
+                return false;
+            }
+        },
+        None => DEFAULT_VERIFY_WINDOW,
+    };
+
+    let secret = match keyring.get_secret(name) {
+        Ok(Some(s)) => s,
+        Ok(None) => {
+            eprintln!("No such account: {}", name);
+            return false;
+        }
+        Err(e) => {
+            eprintln!("Failed to look up the secret: {}", e);
+            return false;
+        }
+    };
+
+    if secret.is_steam() {
+        eprintln!("{} is a Steam Guard account, which verify does not support", name);
+        return false;
+    }
+    if secret.otp_type() == OtpType::Hotp {
+        eprintln!("{} is a counter-based (HOTP) account, which verify does not support", name);
+        return false;
+    }
+
+    let decoded_secret = match Base32Upper::decode_vec(secret.secret()) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("Failed to decode the secret returned by the keyring: {}", e);
+            return false;
+        }
+    };
+
+    let otpgen = OtpGenerator::new(
+        &decoded_secret,
+        secret.period(),
+        secret.digits(),
+        secret.algorithm(),
+    );
+    match otpgen.verify(code, window) {
+        Ok(true) => {
+            println!("Code verified");
+            true
+        }
+        Ok(false) => {
+            println!("Code did not match");
+            false
+        }
+        Err(()) => {
+            eprintln!("Code verification error");
+            false
+        }
+    }
+}
+
+fn parse_backend_kind(cmd_args: &ArgMatches) -> Option<BackendKind> {
+    match cmd_args.get_one::<String>("backend").map(String::as_str) {
+        None | Some("auto") => Some(BackendKind::Auto),
+        Some("secret-service") => Some(BackendKind::SecretService),
+        Some("file") => Some(BackendKind::File),
+        Some(_) => None,
+    }
+}
+
 fn main() {
-    let keyring = match Keyring::new() {
+    let args = get_cli_args().get_matches();
+
+    let Some(backend_kind) = parse_backend_kind(&args) else {
+        eprintln!("Invalid backend, should be one of secret-service, file or auto");
+        exit(1);
+    };
+
+    let keyring = match Keyring::new(backend_kind) {
         Ok(k) => k,
         Err(e) => {
             eprintln!("Failed to connect to the keyring: {}", e);
@@ -145,11 +445,11 @@ fn main() {
         }
     };
 
-    let args = get_cli_args().get_matches();
     let res = match args.subcommand() {
         Some((SUBCOMMAND_GEN, _)) => handle_gen_cmd(&keyring),
         Some((SUBCOMMAND_ADD, cmd_args)) => handle_add_cmd(&keyring, cmd_args),
         Some((SUBCOMMAND_DEL, cmd_args)) => handle_del_cmd(&keyring, cmd_args),
+        Some((SUBCOMMAND_VERIFY, cmd_args)) => handle_verify_cmd(&keyring, cmd_args),
         _ => unreachable!(),
     };
 
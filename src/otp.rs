@@ -1,24 +1,67 @@
 use hmac::{Hmac, Mac};
 use sha1::Sha1;
+use sha2::{Sha256, Sha512};
 use std::{
     fmt::{self, Display},
+    str::FromStr,
     time::{SystemTime, UNIX_EPOCH},
 };
 
+/// The HMAC digest used to derive a code, as named by RFC 6238's `algorithm`
+/// parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl Default for Algorithm {
+    fn default() -> Self {
+        Algorithm::Sha1
+    }
+}
+
+impl Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Sha512 => "SHA512",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "SHA1" => Ok(Algorithm::Sha1),
+            "SHA256" => Ok(Algorithm::Sha256),
+            "SHA512" => Ok(Algorithm::Sha512),
+            _ => Err(()),
+        }
+    }
+}
+
 pub struct OtpGenerator {
     secret_bytes: Vec<u8>,
     interval: u64,
     nr_digits: u8,
+    algorithm: Algorithm,
 }
 
 pub struct OtpCode {
     value: u32,
-    validity_sec: u64,
+    // `None` for counter-based (HOTP) codes, which have no time validity.
+    validity_sec: Option<u64>,
     nr_digits: u8,
 }
 
 impl OtpCode {
-    fn new(value: u32, validity_sec: u64, nr_digits: u8) -> Self {
+    fn new(value: u32, validity_sec: Option<u64>, nr_digits: u8) -> Self {
         OtpCode {
             value,
             validity_sec,
@@ -29,31 +72,71 @@ impl OtpCode {
 
 impl Display for OtpCode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            format!(
-                "{:0>dgts$} (Validity: {}s)",
-                self.value,
-                self.validity_sec,
-                dgts = self.nr_digits as usize
-            )
-        )
+        let value = format!("{:0>dgts$}", self.value, dgts = self.nr_digits as usize);
+        match self.validity_sec {
+            Some(validity_sec) => write!(f, "{} (Validity: {}s)", value, validity_sec),
+            None => write!(f, "{}", value),
+        }
+    }
+}
+
+/// A Steam Guard code: 5 characters drawn from Steam's own alphabet rather
+/// than decimal digits.
+pub struct SteamCode {
+    value: String,
+    validity_sec: u64,
+}
+
+impl SteamCode {
+    fn new(value: String, validity_sec: u64) -> Self {
+        SteamCode {
+            value,
+            validity_sec,
+        }
+    }
+}
+
+impl Display for SteamCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (Validity: {}s)", self.value, self.validity_sec)
+    }
+}
+
+/// Byte-for-byte comparison. Inputs of different lengths are rejected at
+/// once; inputs of equal length are always walked in full instead of
+/// returning on the first mismatching byte.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
     }
+    let mut diff = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+    diff == 0
 }
 
 impl OtpGenerator {
-    pub fn new(secret_bytes: &[u8], interval: u64, nr_digits: u8) -> Self {
+    /* Steam Guard always uses HMAC-SHA1 over a 30s counter, regardless of
+     * the account's configured algorithm/period. */
+    const STEAM_PERIOD: u64 = 30;
+    const STEAM_CODE_LENGTH: usize = 5;
+    const STEAM_CHARS: &'static [u8; 26] = b"23456789BCDFGHJKMNPQRTVWXY";
+
+    pub fn new(secret_bytes: &[u8], interval: u64, nr_digits: u8, algorithm: Algorithm) -> Self {
         OtpGenerator {
             secret_bytes: secret_bytes.to_vec(),
             interval,
             nr_digits,
+            algorithm,
         }
     }
 
-    /* RFC4226 section 5.4 */
+    /* RFC4226 section 5.4, generalized to the digest length produced by
+     * whichever HMAC algorithm was selected (the offset source is always
+     * the low nibble of the digest's last byte). */
     fn dt(hmac_output: &[u8]) -> u32 {
-        let offset_bits = (hmac_output[19] & 0xf) as usize;
+        let offset_bits = (hmac_output[hmac_output.len() - 1] & 0xf) as usize;
         (hmac_output[offset_bits] as u32 & 0x7f) << 24
             | (hmac_output[offset_bits + 1] as u32 & 0xff) << 16
             | (hmac_output[offset_bits + 2] as u32 & 0xff) << 8
@@ -71,21 +154,95 @@ impl OtpGenerator {
         self.interval - (Self::get_time() % self.interval)
     }
 
-    pub fn generate(&self) -> Result<OtpCode, ()> {
-        let time = Self::get_time();
-        let counter = time / self.interval;
-
-        // Compute HOTP(secret, counter)
+    fn sha1_digest(&self, counter: u64) -> Result<Vec<u8>, ()> {
         let mut hmac = Hmac::<Sha1>::new_from_slice(&self.secret_bytes).map_err(|_| ())?;
         hmac.update(&counter.to_be_bytes());
-        let hmac_result = hmac.finalize().into_bytes();
+        Ok(hmac.finalize().into_bytes().to_vec())
+    }
+
+    fn hmac_digest(&self, counter: u64) -> Result<Vec<u8>, ()> {
+        match self.algorithm {
+            Algorithm::Sha1 => self.sha1_digest(counter),
+            Algorithm::Sha256 => {
+                let mut hmac =
+                    Hmac::<Sha256>::new_from_slice(&self.secret_bytes).map_err(|_| ())?;
+                hmac.update(&counter.to_be_bytes());
+                Ok(hmac.finalize().into_bytes().to_vec())
+            }
+            Algorithm::Sha512 => {
+                let mut hmac =
+                    Hmac::<Sha512>::new_from_slice(&self.secret_bytes).map_err(|_| ())?;
+                hmac.update(&counter.to_be_bytes());
+                Ok(hmac.finalize().into_bytes().to_vec())
+            }
+        }
+    }
 
-        let sbits = Self::dt(&hmac_result);
-        let result = sbits % (10_u32.pow(self.nr_digits as u32));
+    fn code_for_counter(&self, counter: u64) -> Result<u32, ()> {
+        let hmac_result = self.hmac_digest(counter)?;
+        let sbits = Self::dt(&hmac_result) as u64;
+        // Checked so a corrupted/out-of-range `nr_digits` (callers are
+        // expected to bound it to MIN_DIGITS..=MAX_DIGITS, see main.rs) cannot
+        // overflow the modulus computation; it yields an error instead.
+        let modulus = 10_u64.checked_pow(self.nr_digits as u32).ok_or(())?;
+        Ok((sbits % modulus) as u32)
+    }
+
+    /// Time-based code (RFC 6238): the counter is derived from the current
+    /// time and the configured period.
+    pub fn generate(&self) -> Result<OtpCode, ()> {
+        let time = Self::get_time();
+        let counter = time / self.interval;
+        let result = self.code_for_counter(counter)?;
         Ok(OtpCode::new(
             result,
-            self.time_to_next_generation(),
+            Some(self.time_to_next_generation()),
             self.nr_digits,
         ))
     }
+
+    /// Counter-based code (RFC 4226) for an explicit, caller-supplied counter.
+    /// Callers are responsible for persisting the incremented counter.
+    pub fn generate_hotp(&self, counter: u64) -> Result<OtpCode, ()> {
+        let result = self.code_for_counter(counter)?;
+        Ok(OtpCode::new(result, None, self.nr_digits))
+    }
+
+    /// Checks `code` against the codes for the current time step and the
+    /// `window` steps on either side of it, to tolerate clock drift between
+    /// the client and whatever generated the secret. The comparison does not
+    /// short-circuit on the first match, so the number of matching steps
+    /// cannot be inferred from timing.
+    pub fn verify(&self, code: &str, window: u64) -> Result<bool, ()> {
+        let current_counter = Self::get_time() / self.interval;
+        let window = window as i64;
+
+        let mut matched = false;
+        for offset in -window..=window {
+            let Some(counter) = current_counter.checked_add_signed(offset) else {
+                continue;
+            };
+            let candidate = self.code_for_counter(counter)?;
+            let candidate = format!("{:0>dgts$}", candidate, dgts = self.nr_digits as usize);
+            matched |= constant_time_eq(candidate.as_bytes(), code.as_bytes());
+        }
+        Ok(matched)
+    }
+
+    pub fn generate_steam(&self) -> Result<SteamCode, ()> {
+        let time = Self::get_time();
+        let counter = time / Self::STEAM_PERIOD;
+
+        let hmac_result = self.sha1_digest(counter)?;
+        let mut sbits = Self::dt(&hmac_result);
+
+        let mut value = String::with_capacity(Self::STEAM_CODE_LENGTH);
+        for _ in 0..Self::STEAM_CODE_LENGTH {
+            value.push(Self::STEAM_CHARS[(sbits % 26) as usize] as char);
+            sbits /= 26;
+        }
+
+        let validity_sec = Self::STEAM_PERIOD - (time % Self::STEAM_PERIOD);
+        Ok(SteamCode::new(value, validity_sec))
+    }
 }
@@ -0,0 +1,149 @@
+use std::{collections::HashMap, fmt};
+
+/// The kind of moving factor an `otpauth://` URI describes: a time step (`totp`)
+/// or an explicit counter (`hotp`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpType {
+    Totp,
+    Hotp,
+}
+
+/// The fields carried by an `otpauth://totp/...` or `otpauth://hotp/...` URI,
+/// as emitted by Google Authenticator and compatible QR codes.
+#[derive(Debug, Clone)]
+pub struct OtpAuthUri {
+    pub otp_type: OtpType,
+    pub account: String,
+    pub issuer: Option<String>,
+    pub secret: String,
+    pub algorithm: Option<String>,
+    pub digits: Option<u8>,
+    pub period: Option<u64>,
+    pub counter: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OtpAuthUriError {
+    msg: String,
+}
+
+impl OtpAuthUriError {
+    fn from_slice(msg: &str) -> Self {
+        Self {
+            msg: String::from(msg),
+        }
+    }
+    fn from_string(msg: String) -> Self {
+        Self { msg }
+    }
+}
+
+impl fmt::Display for OtpAuthUriError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Ok(value) = u8::from_str_radix(&input[i + 1..i + 3], 16) {
+                result.push(value);
+                i += 3;
+                continue;
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next()?;
+            let value = parts.next().unwrap_or("");
+            Some((percent_decode(key), percent_decode(value)))
+        })
+        .collect()
+}
+
+impl OtpAuthUri {
+    const SCHEME_PREFIX: &'static str = "otpauth://";
+
+    /// Parses a `otpauth://totp/Issuer:account?secret=...&issuer=...` style URI.
+    ///
+    /// The label (the path component, before the `?`) is percent-encoded and
+    /// is split on the first colon into an issuer and an account name; the
+    /// `issuer` query parameter, when present, takes precedence over the one
+    /// derived from the label.
+    pub fn parse(uri: &str) -> Result<Self, OtpAuthUriError> {
+        let rest = uri
+            .strip_prefix(Self::SCHEME_PREFIX)
+            .ok_or_else(|| OtpAuthUriError::from_slice("Expected an otpauth:// scheme"))?;
+
+        let (authority_and_path, query) = match rest.split_once('?') {
+            Some((p, q)) => (p, q),
+            None => (rest, ""),
+        };
+
+        let (otp_type, label) = authority_and_path
+            .split_once('/')
+            .ok_or_else(|| OtpAuthUriError::from_slice("Missing account label"))?;
+
+        let otp_type = match otp_type {
+            "totp" => OtpType::Totp,
+            "hotp" => OtpType::Hotp,
+            other => {
+                return Err(OtpAuthUriError::from_string(format!(
+                    "Unsupported otpauth type '{}', expected totp or hotp",
+                    other
+                )))
+            }
+        };
+
+        let label = percent_decode(label);
+        let (issuer_from_label, account) = match label.split_once(':') {
+            Some((issuer, account)) => {
+                (Some(issuer.trim().to_string()), account.trim().to_string())
+            }
+            None => (None, label),
+        };
+
+        let params = parse_query(query);
+
+        let secret = params
+            .get("secret")
+            .cloned()
+            .ok_or_else(|| OtpAuthUriError::from_slice("Missing 'secret' parameter"))?;
+        let issuer = params.get("issuer").cloned().or(issuer_from_label);
+        let algorithm = params.get("algorithm").cloned();
+        let digits = params.get("digits").and_then(|d| d.parse().ok());
+        let period = params.get("period").and_then(|p| p.parse().ok());
+        let counter = params.get("counter").and_then(|c| c.parse().ok());
+
+        if otp_type == OtpType::Hotp && counter.is_none() {
+            return Err(OtpAuthUriError::from_slice(
+                "hotp URIs require a 'counter' parameter",
+            ));
+        }
+
+        Ok(OtpAuthUri {
+            otp_type,
+            account,
+            issuer,
+            secret,
+            algorithm,
+            digits,
+            period,
+            counter,
+        })
+    }
+}